@@ -11,10 +11,24 @@ pub struct RandomxCache {
 
 impl RandomxCache {
     pub fn new(flags: RandomxFlags, key: &[u8]) -> Result<Self, RandomxError> {
+        let unsupported = unsupported_capability_flags(flags);
+
+        if !unsupported.is_empty() {
+            return Err(RandomxError::UnsupportedFlags(unsupported));
+        }
+
         let cache = unsafe { randomx_alloc_cache(flags.bits()) };
 
         if cache.is_null() {
-            return Err(RandomxError::CacheAllocError);
+            // JIT and LARGEPAGES can't be probed ahead of time, so blame
+            // them if requested rather than reporting out-of-memory.
+            let unprobed = flags & (RandomxFlags::JIT | RandomxFlags::LARGEPAGES);
+
+            if !unprobed.is_empty() {
+                return Err(RandomxError::UnsupportedFlags(unprobed));
+            }
+
+            return Err(RandomxError::OutOfMemory);
         }
 
         unsafe {
@@ -27,6 +41,18 @@ impl RandomxCache {
 
         Ok(RandomxCache { cache })
     }
+
+    /// Reinitialise this cache in place with a new key, without
+    /// reallocating its backing memory.
+    pub fn init(&mut self, key: &[u8]) {
+        unsafe {
+            randomx_init_cache(
+                self.cache,
+                key.as_ptr() as *const std::ffi::c_void,
+                key.len().try_into().unwrap(),
+            );
+        }
+    }
 }
 
 impl Drop for RandomxCache {
@@ -15,11 +15,23 @@ impl RandomxDataset {
     pub fn new(flags: RandomxFlags, key: &[u8], num_threads: u8) -> Result<Self, RandomxError> {
         assert!(num_threads > 0);
 
+        let unsupported = unsupported_capability_flags(flags);
+
+        if !unsupported.is_empty() {
+            return Err(RandomxError::UnsupportedFlags(unsupported));
+        }
+
         let cache = RandomxCache::new(flags, key)?;
         let dataset = unsafe { randomx_alloc_dataset(flags.bits()) };
 
         if dataset.is_null() {
-            return Err(RandomxError::DatasetAllocError);
+            // LARGEPAGES can't be probed ahead of time, so blame it if
+            // requested rather than reporting out-of-memory.
+            if flags.contains(RandomxFlags::LARGEPAGES) {
+                return Err(RandomxError::UnsupportedFlags(RandomxFlags::LARGEPAGES));
+            }
+
+            return Err(RandomxError::OutOfMemory);
         }
 
         let mut dataset = RandomxDataset { dataset };
@@ -59,10 +71,8 @@ impl RandomxDataset {
                 let _ = handle.join();
             }
 
-            dataset = match Arc::try_unwrap(dataset_arc) {
-                Ok(dataset) => dataset,
-                Err(_) => return Err(RandomxError::DatasetAllocError),
-            };
+            dataset = Arc::try_unwrap(dataset_arc)
+                .unwrap_or_else(|_| panic!("no thread should still hold a dataset Arc after all handles have joined"));
         }
 
         Ok(dataset)
@@ -1,36 +1,43 @@
 use std::error::Error;
 use std::fmt;
 
+use crate::flags::RandomxFlags;
+
 #[derive(Debug)]
 pub enum RandomxError {
-    /// Occurs when allocating the RandomX cache fails.
-    ///
-    /// Reasons include:
-    ///  * Memory allocation fails
-    ///  * The JIT flag is set but the current platform does not support it
-    ///  * An invalid or unsupported ARGON2 value is set
-    CacheAllocError,
+    /// One or more of the requested flags are not supported by the
+    /// current machine, e.g. `HARDAES` or `ARGON2_AVX2` on a CPU that
+    /// lacks the corresponding instruction set. For those flags, which
+    /// are probed before allocating, contains exactly the unsupported
+    /// ones, so the caller can retry with them cleared. When an
+    /// allocation fails with `JIT` or `LARGEPAGES` requested, which
+    /// cannot be probed ahead of time, contains those requested flags
+    /// as the likely cause instead.
+    UnsupportedFlags(RandomxFlags),
 
-    /// Occurs when allocating a RandomX dataset fails.
-    ///
-    /// Reasons include:
-    ///  * Memory allocation fails
-    DatasetAllocError,
+    /// Allocating a cache, dataset or VM failed and the requested
+    /// flags were otherwise valid, so the most likely cause is that
+    /// the system does not have enough free memory.
+    OutOfMemory,
 
-    /// Occurs when creating a VM fails.
-    ///
-    /// Reasons include:
-    ///  * Scratchpad memory allocation fails
-    ///  * Unsupported flags
-    VmAllocError,
+    /// The requested flags cannot be used together, e.g. `FULLMEM` was
+    /// passed to [`RandomxVm::new`](crate::vm::RandomxVm::new), which
+    /// only accepts a cache, or it was missing from
+    /// [`RandomxVm::new_fast`](crate::vm::RandomxVm::new_fast), which
+    /// only accepts a dataset.
+    IncompatibleFlags { reason: &'static str },
 }
 
 impl fmt::Display for RandomxError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            RandomxError::CacheAllocError => write!(f, "Failed to allocate cache"),
-            RandomxError::DatasetAllocError => write!(f, "Failed to allocate datataset"),
-            RandomxError::VmAllocError => write!(f, "Failed to create VM"),
+            RandomxError::UnsupportedFlags(flags) => {
+                write!(f, "Flags not supported on this machine: {:?}", flags)
+            }
+            RandomxError::OutOfMemory => write!(f, "Not enough free memory"),
+            RandomxError::IncompatibleFlags { reason } => {
+                write!(f, "Incompatible flags: {}", reason)
+            }
         }
     }
 }
@@ -38,9 +45,9 @@ impl fmt::Display for RandomxError {
 impl Error for RandomxError {
     fn description(&self) -> &str {
         match *self {
-            RandomxError::CacheAllocError => "Failed to allocate cache",
-            RandomxError::DatasetAllocError => "Failed to allocate dataset",
-            RandomxError::VmAllocError => "Failed to create VM",
+            RandomxError::UnsupportedFlags(_) => "Flags not supported on this machine",
+            RandomxError::OutOfMemory => "Not enough free memory",
+            RandomxError::IncompatibleFlags { reason } => reason,
         }
     }
 
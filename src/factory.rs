@@ -0,0 +1,307 @@
+use std::mem;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::cache::RandomxCache;
+use crate::dataset::RandomxDataset;
+use crate::error::RandomxError;
+use crate::flags::RandomxFlags;
+use crate::vm::RandomxVm;
+
+/// Indicates whether [`RandomxFactory::get_vm`] reused the cache/dataset
+/// already loaded for the requested seed, or reinitialized them because
+/// the seed changed (e.g. a new Monero epoch key).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedStatus {
+    /// The requested seed matches what was already loaded.
+    NotChanged,
+    /// The requested seed differs from what was loaded; the cache and
+    /// (if applicable) dataset were reinitialized.
+    Changed,
+}
+
+/// The cache/dataset backing a single seed, plus a free-list of idle
+/// VMs already built against it, so that repeat [`RandomxFactory::get_vm`]
+/// calls for an unchanged seed can skip the expensive
+/// `randomx_create_vm` scratchpad allocation entirely.
+struct Generation {
+    cache: Arc<RandomxCache>,
+    dataset: Option<Arc<RandomxDataset>>,
+    pool: Arc<Mutex<Vec<(RandomxFlags, PooledVmInner)>>>,
+}
+
+impl Generation {
+    fn new(seed: &[u8; 32], flags: RandomxFlags, num_threads: u8) -> Result<Self, RandomxError> {
+        let cache = Arc::new(RandomxCache::new(flags, seed)?);
+        let dataset = if flags.contains(RandomxFlags::FULLMEM) {
+            Some(Arc::new(RandomxDataset::new(flags, seed, num_threads)?))
+        } else {
+            None
+        };
+
+        Ok(Generation {
+            cache,
+            dataset,
+            pool: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// A generation is still in use if something other than the trash
+    /// entry itself is holding on to its cache or dataset.
+    fn in_use(&self) -> bool {
+        Arc::strong_count(&self.cache) > 1
+            || self
+                .dataset
+                .as_ref()
+                .map_or(false, |dataset| Arc::strong_count(dataset) > 1)
+    }
+
+    /// Drop every idle VM sitting in the pool, so a generation that has
+    /// been evicted from service (its seed no longer current) doesn't
+    /// keep its cache/dataset alive forever via pooled-but-unused VMs.
+    fn clear_pool(&mut self) {
+        self.pool
+            .lock()
+            .expect("factory lock is never poisoned")
+            .clear();
+    }
+}
+
+enum PooledVmInner {
+    Light(RandomxVm<'static, RandomxCache>),
+    Fast(RandomxVm<'static, RandomxDataset>),
+}
+
+/// A VM checked out of a [`RandomxFactory`].
+///
+/// Built with [`RandomxVm::new_owned`]/[`RandomxVm::new_fast_owned`],
+/// so it carries its own `Arc` to the cache/dataset generation it was
+/// built against and keeps that generation alive for as long as it
+/// lives, even after the factory has moved on to a new seed.
+///
+/// Dropping it returns the VM to its generation's pool instead of
+/// destroying it, so the next [`RandomxFactory::get_vm`] call for the
+/// same seed can reuse it rather than paying for a fresh scratchpad.
+pub struct PooledVm {
+    vm: Option<(RandomxFlags, PooledVmInner)>,
+    pool: Arc<Mutex<Vec<(RandomxFlags, PooledVmInner)>>>,
+}
+
+impl PooledVm {
+    /// Calculate the RandomX hash of some data.
+    pub fn hash(&self, input: &[u8]) -> [u8; 32] {
+        match &self
+            .vm
+            .as_ref()
+            .expect("PooledVm::vm is only None after Drop")
+            .1
+        {
+            PooledVmInner::Light(vm) => vm.hash(input),
+            PooledVmInner::Fast(vm) => vm.hash(input),
+        }
+    }
+}
+
+impl Drop for PooledVm {
+    fn drop(&mut self) {
+        if let Some(vm) = self.vm.take() {
+            self.pool
+                .lock()
+                .expect("factory lock is never poisoned")
+                .push(vm);
+        }
+    }
+}
+
+struct FactoryState {
+    seed: [u8; 32],
+    generation: Generation,
+    /// Previous generations, released at the first seed change after
+    /// every `PooledVm` built from them has been dropped.
+    trash: Vec<Generation>,
+}
+
+/// Caches the RandomX cache/dataset for the current seed and hands out
+/// VMs built against it, reinitializing only when the seed actually
+/// changes.
+///
+/// Shareable across worker threads: wrap in an `Arc` and call
+/// [`get_vm`] from each thread that needs one.
+///
+/// [`get_vm`]: RandomxFactory::get_vm
+pub struct RandomxFactory {
+    num_threads: u8,
+    state: RwLock<FactoryState>,
+}
+
+impl RandomxFactory {
+    /// Build a factory pre-loaded for `seed`.
+    pub fn new(
+        seed: [u8; 32],
+        flags: RandomxFlags,
+        num_threads: u8,
+    ) -> Result<Self, RandomxError> {
+        let generation = Generation::new(&seed, flags, num_threads)?;
+
+        Ok(RandomxFactory {
+            num_threads,
+            state: RwLock::new(FactoryState {
+                seed,
+                generation,
+                trash: Vec::new(),
+            }),
+        })
+    }
+
+    /// Get a VM for `seed`, reinitializing the cache/dataset first if
+    /// `seed` differs from the one currently loaded.
+    ///
+    /// Draws from the pool of VMs already built for this seed/`flags`
+    /// combination when one is idle, instead of always paying for a
+    /// fresh `randomx_create_vm` scratchpad allocation.
+    pub fn get_vm(
+        &self,
+        seed: &[u8; 32],
+        flags: RandomxFlags,
+    ) -> Result<(PooledVm, SeedStatus), RandomxError> {
+        let status = if self.state.read().unwrap().seed != *seed {
+            let mut state = self.state.write().unwrap();
+            // Someone else may have already won the race to reinit.
+            if state.seed != *seed {
+                let generation = Generation::new(seed, flags, self.num_threads)?;
+                let mut old_generation = mem::replace(&mut state.generation, generation);
+                old_generation.clear_pool();
+                state.seed = *seed;
+                state.trash.push(old_generation);
+                // VMs dropped after their generation was evicted went back
+                // into its pool; clear those too, or they keep it in use.
+                for old in state.trash.iter_mut() {
+                    old.clear_pool();
+                }
+                state.trash.retain(Generation::in_use);
+                SeedStatus::Changed
+            } else {
+                SeedStatus::NotChanged
+            }
+        } else {
+            SeedStatus::NotChanged
+        };
+
+        let state = self.state.read().unwrap();
+        let vm = Self::checkout(&state.generation, flags)?;
+
+        Ok((vm, status))
+    }
+
+    fn checkout(generation: &Generation, flags: RandomxFlags) -> Result<PooledVm, RandomxError> {
+        let mut pool = generation
+            .pool
+            .lock()
+            .expect("factory lock is never poisoned");
+
+        if let Some(position) = pool.iter().position(|(pooled_flags, _)| *pooled_flags == flags) {
+            let vm = pool.swap_remove(position);
+            drop(pool);
+            return Ok(PooledVm {
+                vm: Some(vm),
+                pool: generation.pool.clone(),
+            });
+        }
+
+        drop(pool);
+
+        let vm = if flags.contains(RandomxFlags::FULLMEM) {
+            let dataset = generation.dataset.as_ref().ok_or({
+                RandomxError::IncompatibleFlags {
+                    reason: "FULLMEM requested but no dataset is loaded for this seed",
+                }
+            })?;
+            PooledVmInner::Fast(RandomxVm::new_fast_owned(flags, dataset.clone())?)
+        } else {
+            PooledVmInner::Light(RandomxVm::new_owned(flags, generation.cache.clone())?)
+        };
+
+        Ok(PooledVm {
+            vm: Some((flags, vm)),
+            pool: generation.pool.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn reuses_vm_until_seed_changes() {
+        let flags = RandomxFlags::default();
+        let seed_a = [1u8; 32];
+        let seed_b = [2u8; 32];
+
+        let factory = RandomxFactory::new(seed_a, flags, 1).unwrap();
+
+        let (vm, status) = factory.get_vm(&seed_a, flags).unwrap();
+        assert_eq!(status, SeedStatus::NotChanged);
+        let hash_a = vm.hash("RandomX example input\0".as_bytes());
+
+        let (vm, status) = factory.get_vm(&seed_b, flags).unwrap();
+        assert_eq!(status, SeedStatus::Changed);
+        let hash_b = vm.hash("RandomX example input\0".as_bytes());
+
+        assert_ne!(hash_a, hash_b);
+    }
+
+    fn pool_len(factory: &RandomxFactory) -> usize {
+        factory
+            .state
+            .read()
+            .unwrap()
+            .generation
+            .pool
+            .lock()
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    fn returns_dropped_vm_to_the_pool_and_reuses_it() {
+        let flags = RandomxFlags::default();
+        let seed = [3u8; 32];
+
+        let factory = RandomxFactory::new(seed, flags, 1).unwrap();
+        assert_eq!(pool_len(&factory), 0);
+
+        {
+            let (_vm, _) = factory.get_vm(&seed, flags).unwrap();
+            assert_eq!(pool_len(&factory), 0);
+        }
+        assert_eq!(pool_len(&factory), 1);
+
+        let (_vm, _) = factory.get_vm(&seed, flags).unwrap();
+        assert_eq!(
+            pool_len(&factory),
+            0,
+            "checkout should reuse the pooled VM rather than building another"
+        );
+    }
+
+    #[test]
+    fn evicted_generation_drops_its_pooled_vms() {
+        let flags = RandomxFlags::default();
+        let seed_a = [4u8; 32];
+        let seed_b = [5u8; 32];
+
+        let factory = RandomxFactory::new(seed_a, flags, 1).unwrap();
+
+        {
+            let (_vm, _) = factory.get_vm(&seed_a, flags).unwrap();
+        }
+        assert_eq!(pool_len(&factory), 1);
+        let old_pool = factory.state.read().unwrap().generation.pool.clone();
+
+        factory.get_vm(&seed_b, flags).unwrap();
+
+        let old_pool_len = old_pool.lock().unwrap().len();
+        assert_eq!(old_pool_len, 0);
+    }
+}
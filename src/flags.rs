@@ -46,3 +46,18 @@ impl Default for RandomxFlags {
         unsafe { RandomxFlags::from_bits(randomx_get_flags()).unwrap() }
     }
 }
+
+/// Of `flags`, return the subset that this machine cannot actually
+/// provide, so callers can report precisely which flag is the problem
+/// instead of a generic allocation failure.
+///
+/// Only covers HARDAES/ARGON2_SSSE3/ARGON2_AVX2: `RandomxFlags::default()`
+/// only sets those when the CPU supports them, unlike JIT/LARGEPAGES/
+/// SECURE, which are opt-in regardless of support and so can't be probed
+/// this way.
+pub(crate) fn unsupported_capability_flags(flags: RandomxFlags) -> RandomxFlags {
+    let capability_flags =
+        RandomxFlags::HARDAES | RandomxFlags::ARGON2_SSSE3 | RandomxFlags::ARGON2_AVX2;
+
+    (flags & capability_flags) & !RandomxFlags::default()
+}
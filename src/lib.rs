@@ -48,11 +48,13 @@ extern crate randomx_bindings_sys;
 pub mod cache;
 pub mod dataset;
 pub mod error;
+pub mod factory;
 pub mod flags;
 pub mod vm;
 
 pub use crate::cache::*;
 pub use crate::dataset::*;
 pub use crate::error::*;
+pub use crate::factory::*;
 pub use crate::flags::*;
 pub use crate::vm::*;
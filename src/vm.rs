@@ -3,6 +3,7 @@ use std::convert::TryInto;
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ptr;
+use std::sync::Arc;
 
 use crate::cache::*;
 use crate::dataset::*;
@@ -11,51 +12,230 @@ use crate::flags::*;
 
 pub struct RandomxVm<'a, T: 'a> {
     vm: *mut randomx_vm,
+    flags: RandomxFlags,
+    // `Some` for VMs created with `new_owned`/`new_fast_owned`, keeping
+    // their cache/dataset alive for as long as the VM itself instead of
+    // borrowing it for `'a`.
+    owner: Option<Arc<T>>,
     phantom: PhantomData<&'a T>,
 }
 
+/// Validate `flags` before handing them to `randomx_create_vm`, so an
+/// incompatibility is reported precisely instead of surfacing as a
+/// generic null-pointer allocation failure.
+fn check_flags(flags: RandomxFlags, requires_fullmem: bool) -> Result<(), RandomxError> {
+    if flags.contains(RandomxFlags::FULLMEM) != requires_fullmem {
+        let reason = if requires_fullmem {
+            "new_fast requires the FULLMEM flag; use new with a cache for light mode"
+        } else {
+            "new does not accept the FULLMEM flag; use new_fast with a dataset instead"
+        };
+
+        return Err(RandomxError::IncompatibleFlags { reason });
+    }
+
+    let unsupported = unsupported_capability_flags(flags);
+
+    if !unsupported.is_empty() {
+        return Err(RandomxError::UnsupportedFlags(unsupported));
+    }
+
+    Ok(())
+}
+
+/// `randomx_create_vm` returned null. `JIT` and `LARGEPAGES` are opt-in
+/// regardless of CPU support, so `check_flags` can't catch them ahead of
+/// time the way it does `HARDAES`/`ARGON2_SSSE3`/`ARGON2_AVX2`; if either
+/// was requested, report it as the likely cause instead of defaulting to
+/// a generic out-of-memory error.
+fn vm_creation_error(flags: RandomxFlags) -> RandomxError {
+    let unprobed = flags & (RandomxFlags::JIT | RandomxFlags::LARGEPAGES);
+
+    if !unprobed.is_empty() {
+        RandomxError::UnsupportedFlags(unprobed)
+    } else {
+        RandomxError::OutOfMemory
+    }
+}
+
 impl RandomxVm<'_, RandomxCache> {
     pub fn new(flags: RandomxFlags, cache: &'_ RandomxCache) -> Result<Self, RandomxError> {
-        if flags.contains(RandomxFlags::FULLMEM) {
-            return Err(RandomxError::VmAllocError);
+        check_flags(flags, false)?;
+
+        let vm = unsafe { randomx_create_vm(flags.bits(), cache.cache, ptr::null_mut()) };
+
+        if vm.is_null() {
+            return Err(vm_creation_error(flags));
         }
 
+        Ok(RandomxVm {
+            vm,
+            flags,
+            owner: None,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl RandomxVm<'static, RandomxCache> {
+    /// Create a VM that owns its cache, sharing it (cheaply, via the
+    /// `Arc`) rather than borrowing it for some lifetime `'a`.
+    ///
+    /// Unlike [`RandomxVm::new`], the result has no lifetime tied to
+    /// the cache, so it can be stored in a struct, sent to a thread
+    /// pool, or returned from a function without also threading the
+    /// cache's lifetime through the caller.
+    pub fn new_owned(flags: RandomxFlags, cache: Arc<RandomxCache>) -> Result<Self, RandomxError> {
+        check_flags(flags, false)?;
+
         let vm = unsafe { randomx_create_vm(flags.bits(), cache.cache, ptr::null_mut()) };
 
         if vm.is_null() {
-            return Err(RandomxError::VmAllocError);
+            return Err(vm_creation_error(flags));
         }
 
         Ok(RandomxVm {
             vm,
+            flags,
+            owner: Some(cache),
             phantom: PhantomData,
         })
     }
 }
 
+impl Clone for RandomxVm<'static, RandomxCache> {
+    /// Cheaply clone this VM by sharing its underlying cache and
+    /// allocating a new scratchpad, since a `randomx_vm` cannot be
+    /// duplicated directly. Relies on `self.owner` always matching the
+    /// cache actually bound to `self.vm`, which `set_cache` upholds by
+    /// refusing to rebind an owned VM. Panics for a VM built with
+    /// [`RandomxVm::new`] from a `'static` cache, which owns no cache.
+    fn clone(&self) -> Self {
+        let cache = self
+            .owner
+            .clone()
+            .expect("owned VM is always built with an owner cache");
+
+        RandomxVm::new_owned(self.flags, cache).expect("re-creating an owned VM should not fail")
+    }
+}
+
+impl<'a> RandomxVm<'a, RandomxCache> {
+    /// Rebind this VM to a freshly (re)initialized cache, avoiding the
+    /// cost of reallocating the VM's scratchpad.
+    ///
+    /// The new cache must outlive the VM, same as the cache passed to
+    /// [`RandomxVm::new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if this VM was built with [`RandomxVm::new_owned`]: it
+    /// already owns its cache, and rebinding it here would leave
+    /// [`Clone`] cloning from the stale, no-longer-bound cache instead.
+    pub fn set_cache(&mut self, cache: &'a RandomxCache) {
+        assert!(
+            self.owner.is_none(),
+            "set_cache cannot be used on a VM created with new_owned; its cache is fixed for the VM's lifetime"
+        );
+
+        unsafe { randomx_vm_set_cache(self.vm, cache.cache) }
+    }
+}
+
 impl RandomxVm<'_, RandomxDataset> {
     pub fn new_fast(
         flags: RandomxFlags,
         dataset: &'_ RandomxDataset,
     ) -> Result<Self, RandomxError> {
-        if !flags.contains(RandomxFlags::FULLMEM) {
-            return Err(RandomxError::VmAllocError);
+        check_flags(flags, true)?;
+
+        let vm = unsafe { randomx_create_vm(flags.bits(), ptr::null_mut(), dataset.dataset) };
+
+        if vm.is_null() {
+            return Err(vm_creation_error(flags));
         }
 
+        Ok(RandomxVm {
+            vm,
+            flags,
+            owner: None,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl RandomxVm<'static, RandomxDataset> {
+    /// Create a VM that owns its dataset, sharing it (cheaply, via the
+    /// `Arc`) rather than borrowing it for some lifetime `'a`.
+    ///
+    /// Unlike [`RandomxVm::new_fast`], the result has no lifetime tied
+    /// to the dataset, so it can be stored in a struct, sent to a
+    /// thread pool, or returned from a function without also threading
+    /// the dataset's lifetime through the caller.
+    pub fn new_fast_owned(
+        flags: RandomxFlags,
+        dataset: Arc<RandomxDataset>,
+    ) -> Result<Self, RandomxError> {
+        check_flags(flags, true)?;
+
         let vm = unsafe { randomx_create_vm(flags.bits(), ptr::null_mut(), dataset.dataset) };
 
         if vm.is_null() {
-            return Err(RandomxError::VmAllocError);
+            return Err(vm_creation_error(flags));
         }
 
         Ok(RandomxVm {
             vm,
+            flags,
+            owner: Some(dataset),
             phantom: PhantomData,
         })
     }
 }
 
-impl<T> RandomxVm<'_, T> {
+impl Clone for RandomxVm<'static, RandomxDataset> {
+    /// Cheaply clone this VM by sharing its underlying dataset and
+    /// allocating a new scratchpad, since a `randomx_vm` cannot be
+    /// duplicated directly. Relies on `self.owner` always matching the
+    /// dataset actually bound to `self.vm`, which `set_dataset` upholds
+    /// by refusing to rebind an owned VM. Panics for a VM built with
+    /// [`RandomxVm::new_fast`] from a `'static` dataset, which owns no
+    /// dataset.
+    fn clone(&self) -> Self {
+        let dataset = self
+            .owner
+            .clone()
+            .expect("owned VM is always built with an owner dataset");
+
+        RandomxVm::new_fast_owned(self.flags, dataset)
+            .expect("re-creating an owned VM should not fail")
+    }
+}
+
+impl<'a> RandomxVm<'a, RandomxDataset> {
+    /// Rebind this VM to a freshly (re)initialized dataset, avoiding
+    /// the cost of reallocating the VM's scratchpad.
+    ///
+    /// The new dataset must outlive the VM, same as the dataset passed
+    /// to [`RandomxVm::new_fast`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if this VM was built with [`RandomxVm::new_fast_owned`]:
+    /// it already owns its dataset, and rebinding it here would leave
+    /// [`Clone`] cloning from the stale, no-longer-bound dataset
+    /// instead.
+    pub fn set_dataset(&mut self, dataset: &'a RandomxDataset) {
+        assert!(
+            self.owner.is_none(),
+            "set_dataset cannot be used on a VM created with new_fast_owned; its dataset is fixed for the VM's lifetime"
+        );
+
+        unsafe { randomx_vm_set_dataset(self.vm, dataset.dataset) }
+    }
+}
+
+impl<'a, T> RandomxVm<'a, T> {
     /// Calculate the RandomX hash of some data.
     ///
     /// ```no_run
@@ -81,6 +261,173 @@ impl<T> RandomxVm<'_, T> {
             hash.assume_init()
         }
     }
+
+    /// Prime the VM with the first input of a pipelined batch.
+    ///
+    /// Must be followed by a `hash_next` call per subsequent input and
+    /// a final `hash_last` call to retrieve the hash of the last
+    /// input. Prefer [`RandomxVm::batch_hash`], which drives this
+    /// sequence for you. Takes `&mut self`, so no other borrow of the VM
+    /// is active during the call; between calls the caller must keep
+    /// other uses of the VM out of the batch, which `batch_hash` does by
+    /// holding the VM exclusively until the batch is done.
+    pub fn hash_first(&mut self, input: &[u8]) {
+        unsafe {
+            randomx_calculate_hash_first(
+                self.vm,
+                input.as_ptr() as *const std::ffi::c_void,
+                input.len().try_into().unwrap(),
+            );
+        }
+    }
+
+    /// Retrieve the hash of the input passed to the previous
+    /// `hash_first`/`hash_next` call, while beginning computation of
+    /// `input`.
+    pub fn hash_next(&mut self, input: &[u8]) -> [u8; RANDOMX_HASH_SIZE as usize] {
+        let mut hash = MaybeUninit::<[u8; RANDOMX_HASH_SIZE as usize]>::uninit();
+
+        unsafe {
+            randomx_calculate_hash_next(
+                self.vm,
+                input.as_ptr() as *const std::ffi::c_void,
+                input.len().try_into().unwrap(),
+                hash.as_mut_ptr() as *mut std::ffi::c_void,
+            );
+
+            hash.assume_init()
+        }
+    }
+
+    /// Retrieve the hash of the last input passed to `hash_first`/
+    /// `hash_next`, flushing the pipeline.
+    pub fn hash_last(&mut self) -> [u8; RANDOMX_HASH_SIZE as usize] {
+        let mut hash = MaybeUninit::<[u8; RANDOMX_HASH_SIZE as usize]>::uninit();
+
+        unsafe {
+            randomx_calculate_hash_last(self.vm, hash.as_mut_ptr() as *mut std::ffi::c_void);
+
+            hash.assume_init()
+        }
+    }
+
+    /// Hash a sequence of inputs, overlapping each hash's internal
+    /// computation with the next input's using `hash_first`/
+    /// `hash_next`/`hash_last`, so the caller cannot get the ordering
+    /// wrong.
+    ///
+    /// ```no_run
+    /// # // ^ no_run, this is already tested in the actual tests
+    /// use randomx4r::*;
+    /// let flags = RandomxFlags::default();
+    /// let cache = RandomxCache::new(flags, "key".as_bytes())?;
+    /// let mut vm = RandomxVm::new(flags, &cache)?;
+    /// let inputs = ["input0".as_bytes(), "input1".as_bytes()];
+    /// let hashes: Vec<_> = vm.batch_hash(inputs).collect();
+    /// # Ok::<(), RandomxError>(())
+    /// ```
+    pub fn batch_hash<I>(&mut self, inputs: I) -> BatchHasher<'_, 'a, T, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        BatchHasher::new(self, inputs.into_iter())
+    }
+
+    /// Derive the mining commitment for `input` and its RandomX
+    /// `hash`, binding the hash to the exact input that produced it so
+    /// it can be compared against a difficulty target.
+    ///
+    /// `hash` must be the result of hashing `input` with this VM (or
+    /// an equivalent one), e.g. via [`RandomxVm::hash`].
+    pub fn calculate_commitment(
+        &self,
+        input: &[u8],
+        hash: &[u8; RANDOMX_HASH_SIZE as usize],
+    ) -> [u8; RANDOMX_HASH_SIZE as usize] {
+        let mut commitment = MaybeUninit::<[u8; RANDOMX_HASH_SIZE as usize]>::uninit();
+
+        unsafe {
+            randomx_calculate_commitment(
+                input.as_ptr() as *const std::ffi::c_void,
+                input.len().try_into().unwrap(),
+                hash.as_ptr() as *const std::ffi::c_void,
+                commitment.as_mut_ptr() as *mut std::ffi::c_void,
+            );
+
+            commitment.assume_init()
+        }
+    }
+}
+
+/// Iterator adapter returned by [`RandomxVm::batch_hash`] that drives
+/// the `hash_first`/`hash_next`/`hash_last` pipeline over a sequence of
+/// inputs, yielding one hash per input.
+///
+/// `'v` is the lifetime of the exclusive borrow of the VM itself, kept
+/// separate from `'a`, the VM's own cache/dataset lifetime, since `&mut`
+/// is invariant and would otherwise force the two to match.
+pub struct BatchHasher<'v, 'a, T, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    vm: &'v mut RandomxVm<'a, T>,
+    inputs: I,
+    state: BatchHasherState,
+}
+
+enum BatchHasherState {
+    NotStarted,
+    Running,
+    Done,
+}
+
+impl<'v, 'a, T, I> BatchHasher<'v, 'a, T, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    fn new(vm: &'v mut RandomxVm<'a, T>, inputs: I) -> Self {
+        BatchHasher {
+            vm,
+            inputs,
+            state: BatchHasherState::NotStarted,
+        }
+    }
+
+    fn advance(&mut self) -> Option<[u8; RANDOMX_HASH_SIZE as usize]> {
+        match self.inputs.next() {
+            Some(input) => {
+                self.state = BatchHasherState::Running;
+                Some(self.vm.hash_next(input.as_ref()))
+            }
+            None => {
+                self.state = BatchHasherState::Done;
+                Some(self.vm.hash_last())
+            }
+        }
+    }
+}
+
+impl<'v, 'a, T, I> Iterator for BatchHasher<'v, 'a, T, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = [u8; RANDOMX_HASH_SIZE as usize];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.state {
+            BatchHasherState::Done => None,
+            BatchHasherState::NotStarted => {
+                let first = self.inputs.next()?;
+                self.vm.hash_first(first.as_ref());
+                self.advance()
+            }
+            BatchHasherState::Running => self.advance(),
+        }
+    }
 }
 
 impl<T> Drop for RandomxVm<'_, T> {
@@ -123,4 +470,147 @@ mod tests {
 
         assert_eq!(expected, hash);
     }
+
+    #[test]
+    fn batch_hash_matches_individual_hashes() {
+        let flags = RandomxFlags::default();
+        let cache = RandomxCache::new(flags, "RandomX example key\0".as_bytes()).unwrap();
+        let mut vm = RandomxVm::new(flags, &cache).unwrap();
+
+        let inputs = ["RandomX example input\0".as_bytes(), "second input\0".as_bytes()];
+
+        let expected: Vec<_> = inputs.iter().map(|input| vm.hash(input)).collect();
+        let batched: Vec<_> = vm.batch_hash(inputs).collect();
+
+        assert_eq!(expected, batched);
+    }
+
+    #[test]
+    fn set_cache_rebinds_to_new_key() {
+        let flags = RandomxFlags::default();
+        let mut cache = RandomxCache::new(flags, "RandomX example key\0".as_bytes()).unwrap();
+        let mut vm = RandomxVm::new(flags, &cache).unwrap();
+
+        cache.init("a different key\0".as_bytes());
+        vm.set_cache(&cache);
+
+        let rebound_hash = vm.hash("RandomX example input\0".as_bytes());
+
+        let fresh_cache = RandomxCache::new(flags, "a different key\0".as_bytes()).unwrap();
+        let fresh_vm = RandomxVm::new(flags, &fresh_cache).unwrap();
+        let fresh_hash = fresh_vm.hash("RandomX example input\0".as_bytes());
+
+        assert_eq!(fresh_hash, rebound_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "set_cache cannot be used on a VM created with new_owned")]
+    fn set_cache_panics_on_owned_vm() {
+        use std::sync::Arc;
+
+        let flags = RandomxFlags::default();
+        let cache = Arc::new(RandomxCache::new(flags, "RandomX example key\0".as_bytes()).unwrap());
+        let other_cache: &'static RandomxCache = Box::leak(Box::new(
+            RandomxCache::new(flags, "a different key\0".as_bytes()).unwrap(),
+        ));
+        let mut vm = RandomxVm::new_owned(flags, cache).unwrap();
+
+        vm.set_cache(other_cache);
+    }
+
+    #[test]
+    fn owned_vm_matches_borrowed_vm_and_clones() {
+        use std::sync::Arc;
+
+        let flags = RandomxFlags::default();
+        let cache = Arc::new(RandomxCache::new(flags, "RandomX example key\0".as_bytes()).unwrap());
+        let vm = RandomxVm::new_owned(flags, cache).unwrap();
+        let cloned = vm.clone();
+
+        let expected = [
+            138, 72, 229, 249, 219, 69, 171, 121, 217, 8, 5, 116, 196, 216, 25, 84, 254, 106, 198,
+            56, 66, 33, 74, 255, 115, 194, 68, 178, 99, 48, 183, 201,
+        ];
+
+        assert_eq!(expected, vm.hash("RandomX example input\0".as_bytes()));
+        assert_eq!(expected, cloned.hash("RandomX example input\0".as_bytes()));
+    }
+
+    #[test]
+    #[should_panic(expected = "set_dataset cannot be used on a VM created with new_fast_owned")]
+    fn set_dataset_panics_on_owned_vm_keeping_clone_correct() {
+        use std::sync::Arc;
+
+        let flags = RandomxFlags::default() | RandomxFlags::FULLMEM;
+        let dataset = Arc::new(
+            RandomxDataset::new(flags, "RandomX example key\0".as_bytes(), 1).unwrap(),
+        );
+        let other_dataset: &'static RandomxDataset = Box::leak(Box::new(
+            RandomxDataset::new(flags, "a different key\0".as_bytes(), 1).unwrap(),
+        ));
+        let mut vm = RandomxVm::new_fast_owned(flags, dataset).unwrap();
+
+        // If this were allowed, `vm.clone()` would go on to clone from
+        // `self.owner` (the original dataset) while `self.vm` actually
+        // hashes against `other_dataset`, silently cloning the wrong VM.
+        vm.set_dataset(other_dataset);
+    }
+
+    #[test]
+    fn calculate_commitment_is_deterministic_and_differs_from_hash() {
+        let flags = RandomxFlags::default();
+        let cache = RandomxCache::new(flags, "RandomX example key\0".as_bytes()).unwrap();
+        let vm = RandomxVm::new(flags, &cache).unwrap();
+
+        let input = "RandomX example input\0".as_bytes();
+        let hash = vm.hash(input);
+
+        let commitment = vm.calculate_commitment(input, &hash);
+        assert_eq!(commitment, vm.calculate_commitment(input, &hash));
+        assert_ne!(commitment, hash);
+    }
+
+    #[test]
+    fn new_reports_incompatible_fullmem_flag() {
+        let flags = RandomxFlags::default() | RandomxFlags::FULLMEM;
+        let cache = RandomxCache::new(flags, "RandomX example key\0".as_bytes()).unwrap();
+
+        let result = RandomxVm::new(flags, &cache);
+        assert!(matches!(
+            result,
+            Err(RandomxError::IncompatibleFlags { .. })
+        ));
+    }
+
+    #[test]
+    fn new_fast_reports_missing_fullmem_flag() {
+        let flags = RandomxFlags::default();
+        let dataset = RandomxDataset::new(flags, "RandomX example key\0".as_bytes(), 1).unwrap();
+
+        let result = RandomxVm::new_fast(flags, &dataset);
+        assert!(matches!(
+            result,
+            Err(RandomxError::IncompatibleFlags { .. })
+        ));
+    }
+
+    #[test]
+    fn vm_creation_error_blames_jit_or_largepages_over_out_of_memory() {
+        use super::vm_creation_error;
+
+        match vm_creation_error(RandomxFlags::JIT) {
+            RandomxError::UnsupportedFlags(flags) => assert_eq!(flags, RandomxFlags::JIT),
+            other => panic!("expected UnsupportedFlags, got {:?}", other),
+        }
+
+        match vm_creation_error(RandomxFlags::LARGEPAGES) {
+            RandomxError::UnsupportedFlags(flags) => assert_eq!(flags, RandomxFlags::LARGEPAGES),
+            other => panic!("expected UnsupportedFlags, got {:?}", other),
+        }
+
+        assert!(matches!(
+            vm_creation_error(RandomxFlags::HARDAES),
+            RandomxError::OutOfMemory
+        ));
+    }
 }